@@ -25,8 +25,8 @@ fn main() {
 
     #[cfg(unix)]
     {
-        let sig_int = may_signal::Signal::new(may_signal::unix::SIGINT).unwrap();
-        let sig_trm = may_signal::Signal::new(may_signal::unix::SIGTERM).unwrap();
+        let sig_int = may_signal::Signal::new(may_signal::unix::SignalKind::interrupt()).unwrap();
+        let sig_trm = may_signal::Signal::new(may_signal::unix::SignalKind::terminate()).unwrap();
         for _ in 0..3 {
             select!(
                 _ = sig_int.recv().unwrap() => println!("SIGINT received"),
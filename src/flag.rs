@@ -0,0 +1,75 @@
+//! A lightweight, channel-free way to react to a signal.
+//!
+//! Many programs just want to set a flag on `SIGTERM`, or have the first
+//! `SIGINT` trigger a graceful shutdown while a second one kills the process
+//! immediately. Going through a full `Signal` stream and a coroutine to
+//! drive it is more machinery than that needs, so this module binds a
+//! signal directly to an `AtomicBool`.
+
+#![cfg(unix)]
+
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use unix::{self, SignalKind};
+
+/// Registers `flag` to be set to `true` every time `signal` is delivered.
+///
+/// Multiple flags can be registered for the same signal, and a flag is
+/// never cleared automatically; the caller is expected to reset it (e.g.
+/// with `swap`) once it has reacted to it.
+pub fn register(signal: SignalKind, flag: Arc<AtomicBool>) -> io::Result<()> {
+    unix::register_flag(signal, flag)
+}
+
+/// Registers `flag` to be set on the first delivery of `signal`.
+///
+/// On a second delivery (i.e. when `flag` is already set), instead of
+/// setting it again, this restores `signal`'s previous action and re-raises
+/// it, so that action (or the true OS default, if there was none) is what
+/// actually runs once this handler returns and the signal is unblocked;
+/// `status` is accepted for API symmetry but currently unused, since it's
+/// that restored/default action which determines how the process exits.
+///
+/// This gives programs a simple "first signal asks for a graceful shutdown,
+/// second one kills the process right away" pattern without needing a
+/// `Signal` stream.
+pub fn register_conditional_shutdown(
+    signal: SignalKind,
+    status: i32,
+    flag: Arc<AtomicBool>,
+) -> io::Result<()> {
+    unix::register_shutdown(signal, status, flag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    use unix::libc;
+
+    #[test]
+    fn register_sets_flag_when_signal_is_raised() {
+        let flag = Arc::new(AtomicBool::new(false));
+        register(SignalKind::user_defined1(), flag.clone()).unwrap();
+
+        unsafe { libc::raise(libc::SIGUSR1) };
+
+        assert!(flag.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn register_conditional_shutdown_sets_flag_on_first_signal() {
+        let flag = Arc::new(AtomicBool::new(false));
+        register_conditional_shutdown(SignalKind::user_defined2(), 0, flag.clone()).unwrap();
+
+        // Only the first delivery is safe to exercise in-process: the
+        // second restores SIGUSR2's previous (default, terminating)
+        // disposition and re-raises it.
+        unsafe { libc::raise(libc::SIGUSR2) };
+
+        assert!(flag.load(Ordering::SeqCst));
+    }
+}
@@ -32,6 +32,7 @@
 #[doc(hidden)]
 extern crate may;
 
+pub mod flag;
 pub mod unix;
 pub mod windows;
 
@@ -54,12 +55,12 @@ pub fn ctrl_c() -> Signal {
 
     #[cfg(unix)]
     fn ctrl_c_imp() -> io::Result<Signal> {
-        Signal::new(unix::SIGINT)
+        Signal::new(unix::SignalKind::interrupt())
     }
 
     #[cfg(windows)]
     fn ctrl_c_imp() -> io::Result<Signal> {
-        Signal::new(windows::CTRL_C_EVENT)
+        Signal::new(windows::EventKind::ctrl_c())
     }
 
     return ctrl_c_imp().expect("failed to create Signal");
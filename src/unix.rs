@@ -6,12 +6,17 @@
 #![cfg(unix)]
 
 pub extern crate libc;
-use std::io;
+use std::io::{self, Read};
 use std::mem;
 use std::ops::Deref;
 use std::cell::UnsafeCell;
-use std::sync::{Once, ONCE_INIT};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Once, ONCE_INIT};
 
+use may::coroutine;
+use may::os::unix::net::UnixStream;
 use may::sync::Mutex;
 use may::sync::mpsc::{self, Receiver, Sender};
 
@@ -19,12 +24,142 @@ use self::libc::c_int;
 pub use self::libc::{SIGUSR1, SIGUSR2, SIGINT, SIGTERM};
 pub use self::libc::{SIGALRM, SIGHUP, SIGPIPE, SIGQUIT, SIGTRAP};
 
-// Number of different unix signals
-const SIGNUM: usize = 32;
+// Number of preallocated slots per signal for captured `Origin`s. The
+// handler claims a slot with `fetch_add` rather than allocating, so that
+// concurrent deliveries of the same signal on distinct threads write to
+// distinct slots. Slots are reused round-robin, so more than this many
+// concurrent deliveries can share a slot. The driver coroutine only reads
+// the most recently written slot; older captured origins are not delivered.
+const ORIGIN_RING: usize = 8;
+
+// Number of slots to allocate in the signal table. On Linux this stretches
+// all the way up through `SIGRTMAX` so that real-time signals (commonly used
+// for application-defined IPC) can be registered too; other Unixes don't
+// expose a dynamic real-time range so we fall back to a fixed upper bound.
+#[cfg(target_os = "linux")]
+fn signum() -> usize {
+    unsafe { libc::SIGRTMAX() as usize + 1 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn signum() -> usize {
+    33
+}
+
+/// Represents the specific kind of signal to listen for.
+///
+/// This is a typed wrapper around the raw `c_int` values Unix signals are
+/// identified by, so that `Signal::new` can be called with a discoverable,
+/// misuse-resistant set of constructors instead of raw libc constants.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SignalKind(c_int);
+
+impl SignalKind {
+    /// Allows for listening to any valid OS signal.
+    ///
+    /// For example, this can be used for listening for platform-specific
+    /// signals.
+    pub const fn from_raw(signum: c_int) -> Self {
+        SignalKind(signum)
+    }
+
+    /// Get the signal's numeric value.
+    pub const fn as_raw(&self) -> c_int {
+        self.0
+    }
+
+    /// Represents the `SIGINT` signal.
+    ///
+    /// On Unix systems this signal is sent to a process when its controlling
+    /// terminal sends a termination signal, commonly triggered by the user
+    /// pressing Ctrl+C.
+    pub const fn interrupt() -> Self {
+        SignalKind(SIGINT)
+    }
+
+    /// Represents the `SIGTERM` signal.
+    ///
+    /// On Unix systems this signal is sent to a process to request its
+    /// termination. Unlike `SIGKILL`, this signal can be blocked, handled,
+    /// and ignored.
+    pub const fn terminate() -> Self {
+        SignalKind(SIGTERM)
+    }
+
+    /// Represents the `SIGHUP` signal.
+    ///
+    /// On Unix systems this signal is sent when the terminal is disconnected.
+    pub const fn hangup() -> Self {
+        SignalKind(SIGHUP)
+    }
+
+    /// Represents the `SIGUSR1` signal.
+    ///
+    /// On Unix systems this is a user defined signal.
+    pub const fn user_defined1() -> Self {
+        SignalKind(SIGUSR1)
+    }
+
+    /// Represents the `SIGUSR2` signal.
+    ///
+    /// On Unix systems this is a user defined signal.
+    pub const fn user_defined2() -> Self {
+        SignalKind(SIGUSR2)
+    }
+}
+
+/// Origin metadata for a received signal, as reported by the kernel in the
+/// `siginfo_t` passed to the handler.
+///
+/// This is only populated for streams created with [`Signal::with_info`],
+/// which lets a recipient tell, for instance, which process sent it a
+/// `SIGTERM` or `SIGUSR1`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Origin {
+    /// The signal number that was delivered, i.e. `si_signo`.
+    pub signal: c_int,
+    /// The signal code, i.e. `si_code`, describing why the signal was sent.
+    pub code: c_int,
+    /// The pid of the process that sent the signal, i.e. `si_pid`.
+    pub pid: libc::pid_t,
+    /// The real user id of the sending process, i.e. `si_uid`.
+    pub uid: libc::uid_t,
+}
 
 struct SignalInfo {
+    // Set to true by the signal handler, and swapped back to false by the
+    // driver coroutine once the pending recipients have been notified. Only
+    // ever touched with `SeqCst` so the handler stays async-signal-safe.
+    pending: AtomicBool,
     // The ones interested in this signal
     recipients: Mutex<Vec<Box<Sender<()>>>>,
+    // The ones interested in this signal's `Origin`
+    origin_recipients: Mutex<Vec<Box<Sender<Origin>>>>,
+    // Set to true by the handler whenever it has captured a fresh `Origin`,
+    // and swapped back to false once the driver has broadcast it.
+    origin_pending: AtomicBool,
+    // Preallocated ring of captured origins. `origin_next` is claimed with
+    // `fetch_add` so that concurrent handler invocations (on different OS
+    // threads, for the same signal) each write into a distinct slot instead
+    // of racing to pick a "free" one out of a small reused set; `origin_current`
+    // publishes the index of the most recently written slot for the driver
+    // to read. Allocating (e.g. `Box`) from the handler is not an option
+    // here: `malloc` is not async-signal-safe and can deadlock the process
+    // if the interrupted thread already held glibc's allocator lock.
+    origin_ring: [UnsafeCell<Origin>; ORIGIN_RING],
+    origin_next: AtomicUsize,
+    origin_current: AtomicUsize,
+    // Flags registered through the `flag` module. `flags`/`shutdown` are the
+    // source of truth and are only ever touched by registration calls
+    // (outside of signal context); the handler instead reads the published
+    // `*_snapshot` pointers, since locking a `Mutex` from signal context is
+    // not async-signal-safe. Each registration republishes a fresh snapshot
+    // and leaks the previous one, mirroring how `GLOBALS` itself is never
+    // freed.
+    flags: Mutex<Vec<Arc<AtomicBool>>>,
+    flags_snapshot: AtomicPtr<Vec<Arc<AtomicBool>>>,
+    shutdown: Mutex<Vec<ShutdownFlag>>,
+    shutdown_snapshot: AtomicPtr<Vec<ShutdownFlag>>,
     init: Once,
     initialized: UnsafeCell<bool>,
     prev: UnsafeCell<libc::sigaction>,
@@ -33,16 +168,36 @@ struct SignalInfo {
 impl Default for SignalInfo {
     fn default() -> SignalInfo {
         SignalInfo {
+            pending: AtomicBool::new(false),
             init: ONCE_INIT,
             initialized: UnsafeCell::new(false),
             recipients: Mutex::new(Vec::new()),
+            origin_recipients: Mutex::new(Vec::new()),
+            origin_pending: AtomicBool::new(false),
+            origin_ring: unsafe { mem::zeroed() },
+            origin_next: AtomicUsize::new(0),
+            origin_current: AtomicUsize::new(0),
+            flags: Mutex::new(Vec::new()),
+            flags_snapshot: AtomicPtr::new(ptr::null_mut()),
+            shutdown: Mutex::new(Vec::new()),
+            shutdown_snapshot: AtomicPtr::new(ptr::null_mut()),
             prev: UnsafeCell::new(unsafe { mem::zeroed() }),
         }
     }
 }
 
+// An `AtomicBool` bound to a signal by `flag::register_conditional_shutdown`.
+#[derive(Clone)]
+struct ShutdownFlag {
+    flag: Arc<AtomicBool>,
+}
+
 struct Globals {
-    signals: [SignalInfo; SIGNUM],
+    signals: Vec<SignalInfo>,
+    // Write end of the self-pipe. The signal handler only ever does a
+    // best-effort, non-blocking `write()` of a single byte here to wake up
+    // the driver coroutine; it never touches the read end.
+    pipe_write: RawFd,
 }
 
 static mut GLOBALS: *mut Globals = 0 as *mut Globals;
@@ -52,15 +207,114 @@ fn globals() -> &'static Globals {
 
     unsafe {
         INIT.call_once(|| {
+            let (read_end, write_end) = create_pipe();
             let globals = Globals {
-                signals: Default::default(),
+                signals: (0..signum()).map(|_| SignalInfo::default()).collect(),
+                pipe_write: write_end,
             };
             GLOBALS = Box::into_raw(Box::new(globals));
+
+            coroutine::spawn(move || drive(read_end));
         });
         &*GLOBALS
     }
 }
 
+// Creates a non-blocking self-pipe (backed by a `socketpair`) used to wake
+// the driver coroutine up from async-signal-safe context. Returns the read
+// end, already wrapped as a coroutine-aware `UnixStream`, and the raw write
+// end, which the signal handler writes to directly with `libc::write`.
+fn create_pipe() -> (UnixStream, RawFd) {
+    unsafe {
+        let mut fds = [0 as c_int; 2];
+        if libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) != 0 {
+            panic!("failed to create self-pipe: {}", io::Error::last_os_error());
+        }
+        set_nonblocking(fds[0]);
+        set_nonblocking(fds[1]);
+        (UnixStream::from_raw_fd(fds[0]), fds[1])
+    }
+}
+
+unsafe fn set_nonblocking(fd: RawFd) {
+    let flags = libc::fcntl(fd, libc::F_GETFL, 0);
+    libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+}
+
+// Runs forever in its own `may` coroutine. Blocks reading the self-pipe;
+// every time a byte (or more) shows up it drains the pipe and then scans
+// every registered signal for one whose `pending` flag was set by the
+// handler, broadcasting to its recipients outside of signal context.
+fn drive(mut read_end: UnixStream) {
+    let mut buf = [0u8; 128];
+    loop {
+        match read_end.read(&mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+
+        for slot in globals().signals.iter() {
+            if slot.pending.swap(false, Ordering::SeqCst) {
+                for tx in slot.recipients.lock().unwrap().iter() {
+                    let _ = tx.send(());
+                }
+            }
+
+            if slot.origin_pending.swap(false, Ordering::SeqCst) {
+                let idx = slot.origin_current.load(Ordering::SeqCst);
+                let origin = unsafe { *slot.origin_ring[idx].get() };
+                for tx in slot.origin_recipients.lock().unwrap().iter() {
+                    let _ = tx.send(origin);
+                }
+            }
+        }
+    }
+}
+
+// Writes a single byte to the write end of the self-pipe. Only ever called
+// from the signal handler, so this must stay async-signal-safe: a raw
+// `libc::write` retried on `EINTR` and otherwise ignored (in particular
+// `EAGAIN`/`EWOULDBLOCK`, since the driver only needs to be woken, not to
+// see every byte).
+fn wake_driver(fd: RawFd) {
+    let byte: u8 = 1;
+    loop {
+        let ret = unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+        if ret >= 0 {
+            return;
+        }
+        match io::Error::last_os_error().kind() {
+            io::ErrorKind::Interrupted => continue,
+            _ => return,
+        }
+    }
+}
+
+// Extracts the origin of a signal delivery out of `info` and publishes it
+// for the driver coroutine to pick up. Claims a slot in the preallocated
+// `origin_ring` via `fetch_add` and writes the `Origin` directly into it, so
+// no allocation happens in the handler; since every invocation claims its
+// own slot, two handler invocations running concurrently on different
+// threads for the same signal can never tear or clobber each other's write.
+unsafe fn capture_origin(slot: &SignalInfo, info: *mut libc::siginfo_t) {
+    if info.is_null() {
+        return;
+    }
+
+    let idx = slot.origin_next.fetch_add(1, Ordering::SeqCst) % ORIGIN_RING;
+    *slot.origin_ring[idx].get() = Origin {
+        signal: (*info).si_signo,
+        code: (*info).si_code,
+        pid: (*info).si_pid(),
+        uid: (*info).si_uid(),
+    };
+    slot.origin_current.store(idx, Ordering::SeqCst);
+    slot.origin_pending.store(true, Ordering::SeqCst);
+}
+
 /// Our global signal handler for all signals registered by this module.
 ///
 /// The purpose of this signal handler is to primarily:
@@ -80,11 +334,39 @@ extern "C" fn handler(signum: c_int, info: *mut libc::siginfo_t, ptr: *mut libc:
             None => return,
         };
 
-        // broadcast the signal
-        for tx in slot.recipients.lock().unwrap().iter() {
-            tx.send(()).unwrap();
+        slot.pending.store(true, Ordering::SeqCst);
+        capture_origin(slot, info);
+
+        let flags = slot.flags_snapshot.load(Ordering::SeqCst);
+        if !flags.is_null() {
+            for flag in (*flags).iter() {
+                flag.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let shutdowns = slot.shutdown_snapshot.load(Ordering::SeqCst);
+        if !shutdowns.is_null() {
+            for sd in (*shutdowns).iter() {
+                if sd.flag.swap(true, Ordering::SeqCst) {
+                    // This signal is blocked on the current thread for the
+                    // duration of this handler (we don't set SA_NODEFER), so
+                    // `raise` here only marks it pending; it can't invoke
+                    // `prev`'s action synchronously. Restore the disposition
+                    // and return instead: once this handler returns the
+                    // signal is unblocked and the kernel delivers the
+                    // now-pending one, running `prev`'s real action (or the
+                    // true OS default, e.g. a core dump / correct
+                    // WIFSIGNALED exit status) exactly as if we'd never
+                    // installed a handler for it.
+                    libc::sigaction(signum, slot.prev.get(), ptr::null_mut());
+                    libc::raise(signum);
+                    return;
+                }
+            }
         }
 
+        wake_driver((*GLOBALS).pipe_write);
+
         let fnptr = (*slot.prev.get()).sa_sigaction;
         if fnptr == 0 || fnptr == libc::SIG_DFL || fnptr == libc::SIG_IGN {
             return;
@@ -135,31 +417,85 @@ fn signal_enable(signal: c_int) -> io::Result<()> {
     }
 }
 
+// Registers `flag` to be set whenever `signal` is delivered. Used by the
+// `flag` module.
+pub(crate) fn register_flag(kind: SignalKind, flag: Arc<AtomicBool>) -> io::Result<()> {
+    let signal = kind.as_raw();
+    try!(signal_enable(signal));
+
+    let slot = &globals().signals[signal as usize];
+    let mut flags = slot.flags.lock().unwrap();
+    flags.push(flag);
+    let snapshot = Box::new(flags.clone());
+    slot.flags_snapshot.store(Box::into_raw(snapshot), Ordering::SeqCst);
+    Ok(())
+}
+
+// Registers `flag` to be set on the first delivery of `signal`, and on the
+// second to restore the signal's previous action and re-raise it so that
+// action (or the true OS default) runs. `_status` is accepted for API
+// symmetry with `flag::register_conditional_shutdown` but unused: the
+// restored/default action is what actually determines how the process
+// exits. Used by the `flag` module.
+pub(crate) fn register_shutdown(kind: SignalKind, _status: i32, flag: Arc<AtomicBool>) -> io::Result<()> {
+    let signal = kind.as_raw();
+    try!(signal_enable(signal));
+
+    let slot = &globals().signals[signal as usize];
+    let mut shutdown = slot.shutdown.lock().unwrap();
+    shutdown.push(ShutdownFlag { flag: flag });
+    let snapshot = Box::new(shutdown.clone());
+    slot.shutdown_snapshot.store(Box::into_raw(snapshot), Ordering::SeqCst);
+    Ok(())
+}
+
+// Selects which fanout list inside a `SignalInfo` a `Signal<T>` registers
+// itself with. `()` streams and `Origin` streams are delivered through
+// separate lists so that the (cheap, common) plain case never pays for
+// carrying origin metadata it doesn't want.
+trait Recipients: Sized {
+    fn recipients(slot: &SignalInfo) -> &Mutex<Vec<Box<Sender<Self>>>>;
+}
+
+impl Recipients for () {
+    fn recipients(slot: &SignalInfo) -> &Mutex<Vec<Box<Sender<()>>>> {
+        &slot.recipients
+    }
+}
+
+impl Recipients for Origin {
+    fn recipients(slot: &SignalInfo) -> &Mutex<Vec<Box<Sender<Origin>>>> {
+        &slot.origin_recipients
+    }
+}
+
 /// An implementation of `Stream` for receiving a particular type of signal.
 ///
-/// This structure deref to mpsc::Receiver<()> and represents notifications
+/// This structure deref to `mpsc::Receiver<T>` and represents notifications
 /// of the current process receiving a particular signal. The signal being
-/// listened for is passed to `Signal::new`, and every signal is then
-/// yielded as each element for the stream.
+/// listened for is passed to `Signal::new` (or `Signal::with_info`), and
+/// each delivery is then yielded as an element of the stream; deliveries of
+/// the same signal that arrive before the driver coroutine runs are
+/// coalesced into a single element.
 ///
-pub struct Signal {
+pub struct Signal<T = ()> {
     signal: c_int,
     // Used only as an identifier. We place the real sender into a Box, so it
     // stays on the same address forever. That gives us a unique pointer, so we
     // can use this to identify the sender in a Vec and delete it when we are
     // dropped.
-    id: *const Sender<()>,
-    rx: Receiver<()>,
+    id: *const Sender<T>,
+    rx: Receiver<T>,
 }
 
 // The raw pointer prevents the compiler from determining it as Send
 // automatically. But the only thing we use the raw pointer for is to identify
 // the correct Box to delete, not manipulate any data through that.
-unsafe impl Send for Signal {}
+unsafe impl<T> Send for Signal<T> {}
 
-impl Signal {
+impl Signal<()> {
     /// Creates a new stream which will receive notifications when the current
-    /// process receives the signal `signal`.
+    /// process receives the signal identified by `kind`.
     ///
     /// The `Signal` stream is an infinite stream which will receive
     /// notifications whenever a signal is received. More documentation can be
@@ -171,7 +507,33 @@ impl Signal {
     /// A `Signal` stream can be created for a particular signal number
     /// multiple times. When a signal is received then all the associated
     /// channels will receive the signal notification.
-    pub fn new(signal: c_int) -> io::Result<Signal> {
+    pub fn new(kind: SignalKind) -> io::Result<Signal<()>> {
+        Signal::register(kind)
+    }
+
+    /// Creates a new stream from a raw signal number, such as `unix::SIGINT`.
+    #[deprecated(since = "0.2.0", note = "use `Signal::new` with a `SignalKind` instead")]
+    pub fn with_raw_signum(signal: c_int) -> io::Result<Signal<()>> {
+        Signal::new(SignalKind::from_raw(signal))
+    }
+}
+
+impl Signal<Origin> {
+    /// Creates a new stream which, in addition to notifying on every
+    /// delivery of the signal identified by `kind`, also carries its
+    /// `Origin`: the sending process's pid/uid and the raw `si_code`. This
+    /// lets a recipient distinguish, for instance, who sent it a `SIGTERM`
+    /// or `SIGUSR1`. When several deliveries are coalesced into one element,
+    /// that element carries the `Origin` of the most recent one.
+    pub fn with_info(kind: SignalKind) -> io::Result<Signal<Origin>> {
+        Signal::register(kind)
+    }
+}
+
+impl<T: Recipients> Signal<T> {
+    fn register(kind: SignalKind) -> io::Result<Signal<T>> {
+        let signal = kind.as_raw();
+
         // Turn the signal delivery on once we are ready for it
         try!(signal_enable(signal));
 
@@ -181,7 +543,7 @@ impl Signal {
         let tx = Box::new(tx);
         let id: *const _ = &*tx;
         let idx = signal as usize;
-        globals().signals[idx].recipients.lock().unwrap().push(tx);
+        T::recipients(&globals().signals[idx]).lock().unwrap().push(tx);
         Ok(Signal {
             rx: rx,
             id: id,
@@ -190,17 +552,37 @@ impl Signal {
     }
 }
 
-impl Deref for Signal {
-    type Target = mpsc::Receiver<()>;
-    fn deref(&self) -> &mpsc::Receiver<()> {
+impl<T> Deref for Signal<T> {
+    type Target = mpsc::Receiver<T>;
+    fn deref(&self) -> &mpsc::Receiver<T> {
         &self.rx
     }
 }
 
-impl Drop for Signal {
+impl<T: Recipients> Drop for Signal<T> {
     fn drop(&mut self) {
         let idx = self.signal as usize;
-        let mut list = globals().signals[idx].recipients.lock().unwrap();
+        let mut list = T::recipients(&globals().signals[idx]).lock().unwrap();
         list.retain(|sender| &**sender as *const _ != self.id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_stream_yields_on_raise() {
+        let s = Signal::new(SignalKind::user_defined1()).unwrap();
+        unsafe { libc::raise(libc::SIGUSR1) };
+        s.recv().unwrap();
+    }
+
+    #[test]
+    fn signal_with_info_yields_origin_on_raise() {
+        let s = Signal::with_info(SignalKind::user_defined1()).unwrap();
+        unsafe { libc::raise(libc::SIGUSR1) };
+        let origin = s.recv().unwrap();
+        assert_eq!(origin.signal, libc::SIGUSR1);
+    }
+}
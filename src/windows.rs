@@ -3,7 +3,8 @@
 //! This module is only defined on Windows and contains the primary `Event` type
 //! for receiving notifications of events. These events are listened for via the
 //! `SetConsoleCtrlHandler` function which receives events of the type
-//! `CTRL_C_EVENT` and `CTRL_BREAK_EVENT`
+//! `CTRL_C_EVENT`, `CTRL_BREAK_EVENT`, `CTRL_CLOSE_EVENT`, `CTRL_LOGOFF_EVENT`
+//! and `CTRL_SHUTDOWN_EVENT`
 
 #![cfg(windows)]
 extern crate winapi;
@@ -17,16 +18,74 @@ use may::sync::mpsc::{self, Receiver, Sender};
 use self::winapi::shared::minwindef::*;
 
 pub use self::winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+pub use self::winapi::um::wincon::{CTRL_CLOSE_EVENT, CTRL_LOGOFF_EVENT, CTRL_SHUTDOWN_EVENT};
 
 extern "system" {
     fn SetConsoleCtrlHandler(HandlerRoutine: usize, Add: BOOL) -> BOOL;
 }
 
-// Number of different windows signals
-// only CTRL_C and CTRL_BREAK supported
-const SIGNUM: usize = 2;
+// Number of different windows signals: CTRL_C, CTRL_BREAK, CTRL_CLOSE,
+// CTRL_LOGOFF and CTRL_SHUTDOWN.
+const SIGNUM: usize = 5;
 const CTRL_C_SLOT: usize = 0;
 const CTRL_BREAK_SLOT: usize = 1;
+const CTRL_CLOSE_SLOT: usize = 2;
+const CTRL_LOGOFF_SLOT: usize = 3;
+const CTRL_SHUTDOWN_SLOT: usize = 4;
+
+/// Represents the specific kind of console control event to listen for.
+///
+/// This is a typed wrapper around the raw `DWORD` values the Windows console
+/// control handler is identified by, so that `Signal::new` can be called with
+/// a discoverable, misuse-resistant set of constructors instead of raw winapi
+/// constants.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EventKind(DWORD);
+
+impl EventKind {
+    /// Allows for listening to any valid console control event.
+    pub const fn from_raw(event: DWORD) -> Self {
+        EventKind(event)
+    }
+
+    /// Get the event's numeric value.
+    pub const fn as_raw(&self) -> DWORD {
+        self.0
+    }
+
+    /// Represents the `CTRL_C_EVENT` event, generated when the user presses
+    /// Ctrl+C.
+    pub const fn ctrl_c() -> Self {
+        EventKind(CTRL_C_EVENT)
+    }
+
+    /// Represents the `CTRL_BREAK_EVENT` event, generated when the user
+    /// presses Ctrl+Break.
+    pub const fn ctrl_break() -> Self {
+        EventKind(CTRL_BREAK_EVENT)
+    }
+
+    /// Represents the `CTRL_CLOSE_EVENT` event, generated when the console
+    /// window is closed. The OS only gives the process a short grace period
+    /// to handle this before terminating it.
+    pub const fn ctrl_close() -> Self {
+        EventKind(CTRL_CLOSE_EVENT)
+    }
+
+    /// Represents the `CTRL_LOGOFF_EVENT` event, generated when the user
+    /// logs off. The OS only gives the process a short grace period to
+    /// handle this before terminating it.
+    pub const fn ctrl_logoff() -> Self {
+        EventKind(CTRL_LOGOFF_EVENT)
+    }
+
+    /// Represents the `CTRL_SHUTDOWN_EVENT` event, generated when the system
+    /// is shutting down. The OS only gives the process a short grace period
+    /// to handle this before terminating it.
+    pub const fn ctrl_shutdown() -> Self {
+        EventKind(CTRL_SHUTDOWN_EVENT)
+    }
+}
 
 struct SignalInfo {
     // The ones interested in this signal
@@ -70,11 +129,15 @@ fn globals() -> &'static Globals {
     }
 }
 
-/// global signal handler for CTRL_C and CTRL_BREAK
+/// global signal handler for CTRL_C, CTRL_BREAK, CTRL_CLOSE, CTRL_LOGOFF and
+/// CTRL_SHUTDOWN
 unsafe extern "system" fn handler(ty: DWORD) -> BOOL {
     let event = match ty {
         CTRL_C_EVENT => CTRL_C_SLOT,
         CTRL_BREAK_EVENT => CTRL_BREAK_SLOT,
+        CTRL_CLOSE_EVENT => CTRL_CLOSE_SLOT,
+        CTRL_LOGOFF_EVENT => CTRL_LOGOFF_SLOT,
+        CTRL_SHUTDOWN_EVENT => CTRL_SHUTDOWN_SLOT,
         _ => return FALSE,
     };
 
@@ -83,7 +146,10 @@ unsafe extern "system" fn handler(ty: DWORD) -> BOOL {
         None => unreachable!(),
     };
 
-    // broadcast the signal
+    // Broadcast the event. For CTRL_CLOSE/LOGOFF/SHUTDOWN the OS only gives
+    // the process a short grace period before terminating it, so this must
+    // not block: `recipients` is only ever held briefly to push/pop/iterate,
+    // and `send` on the underlying channel never blocks the sender.
     for tx in slot.recipients.lock().unwrap().iter() {
         tx.send(()).unwrap();
     }
@@ -123,10 +189,13 @@ impl Signal {
     /// A `Signal` stream can be created for a particular signal number
     /// multiple times. When a signal is received then all the associated
     /// channels will receive the signal notification.
-    pub fn new(signal: DWORD) -> io::Result<Signal> {
-        let slot = match signal {
+    pub fn new(signal: EventKind) -> io::Result<Signal> {
+        let slot = match signal.as_raw() {
             CTRL_C_EVENT => CTRL_C_SLOT,
             CTRL_BREAK_EVENT => CTRL_BREAK_SLOT,
+            CTRL_CLOSE_EVENT => CTRL_CLOSE_SLOT,
+            CTRL_LOGOFF_EVENT => CTRL_LOGOFF_SLOT,
+            CTRL_SHUTDOWN_EVENT => CTRL_SHUTDOWN_SLOT,
             _ => return Err(io::Error::new(io::ErrorKind::Other, "invalide signal")),
         };
 
@@ -140,6 +209,13 @@ impl Signal {
             signal: slot,
         })
     }
+
+    /// Creates a new stream from a raw console control event, such as
+    /// `windows::CTRL_C_EVENT`.
+    #[deprecated(since = "0.2.0", note = "use `Signal::new` with an `EventKind` instead")]
+    pub fn with_raw_event(signal: DWORD) -> io::Result<Signal> {
+        Signal::new(EventKind::from_raw(signal))
+    }
 }
 
 impl Deref for Signal {